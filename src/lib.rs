@@ -1,23 +1,86 @@
 use thiserror::Error;
 use core::panic;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use core::fmt::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use swc_common::errors::{ColorConfig, Handler};
 use swc_common::sync::Lrc;
-use swc_common::SourceMap;
-use swc_ecma_ast::{Expr, Ident, FnDecl, Lit, MemberExpr, MemberProp, Module, Pat, VarDecl, VarDeclarator};
+use swc_common::{Span, Spanned, SourceMap};
+use serde::Serialize;
+use swc_ecma_ast::{BinExpr, BinaryOp, BlockStmt, BreakStmt, CallExpr, Callee, ContinueStmt, Decl, Expr, Ident, FnDecl, ForStmt, IfStmt, ImportDecl, Lit, MemberExpr, MemberProp, Module, ModuleDecl, ModuleItem, Pat, ReturnStmt, Stmt, UnaryExpr, UnaryOp, UpdateExpr, UpdateOp, VarDecl, VarDeclKind, VarDeclOrExpr, VarDeclarator, WhileStmt};
 use swc_ecma_parser::{lexer::Lexer, Parser, StringInput, Syntax};
 use swc_ecma_visit::{Visit, VisitWith};
 
 pub struct Compiler<'a> {
     bytecode: Vec<u8>,
+    constants: Vec<Constant>,
     local_count: usize,
     locals: [Option<Local>; 256],
     current_scope_depth: usize,
-    scope: HashMap<String, usize>,
     enclosing: Option<&'a Compiler<'a>>,
+    base_dir: PathBuf,
+    // specifier -> (file id, kind), resolved up front by `resolve_imports` so
+    // that the `Visit` pass never needs to hold a borrowed loader closure
+    // (doing so made the whole struct invariant over `'a` and broke
+    // `new_enclosing`)
+    resolved_imports: HashMap<String, (FileId, FileKind)>,
+    pending_modules: Vec<(FileId, PathBuf)>,
+    errors: Vec<CompileError>,
+    // one entry per loop currently being compiled, innermost last, so
+    // `break`/`continue` resolve to the nearest enclosing loop
+    loops: Vec<LoopContext>,
+}
+
+// backpatch bookkeeping for one loop: `break`/`continue` emit a forward jump
+// on the spot and record its offset here, to be patched once the loop knows
+// where it exits (for `break`) or where its back-edge starts (for `continue`)
+struct LoopContext {
+    // the scope depth outside the loop's own body scope - `break`/`continue`
+    // unwind any locals deeper than this before jumping, since the jump
+    // skips the body's normal `exit_scope`
+    depth: usize,
+    break_jumps: Vec<usize>,
+    continue_jumps: Vec<usize>,
+}
+
+// a numeric handle for a file participating in a compiled module graph,
+// assigned by whatever loader resolves `import`/embed specifiers
+pub type FileId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Module,
+    Embed,
+}
+
+// resolves an `import`/embed specifier found at `path` to a `FileId`,
+// named so the shape doesn't have to be spelled out at every call site
+pub type Loader<'a> = dyn FnMut(&str, &Path, FileKind) -> Result<FileId, CompileError> + 'a;
+
+// the result of compiling a full module graph from one entry point: every
+// file reachable via `import`, keyed by the FileId its loader assigned
+#[derive(Debug, Default)]
+pub struct Program {
+    pub entry: FileId,
+    pub units: HashMap<FileId, CompiledUnit>,
+}
+
+// a value the compiler has lifted out of the instruction stream so it can be
+// referenced by a small pool index instead of inlined every time it's used
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Constant {
+    String(String),
+    Float64(f64),
+    Regex(String, String),
+    Function(VirtualFunction),
+    Embed(Vec<u8>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct CompiledUnit {
+    pub bytecode: Vec<u8>,
+    pub constants: Vec<Constant>,
 }
 
 #[derive(Error, Debug)]
@@ -28,14 +91,30 @@ pub enum CompileError {
     ParseError(String),
     #[error("too many locals, max number of locals supported is 256")]
     TooManyLocals,
+    #[error("too much code to jump over, jump offsets are limited to u16::MAX bytes")]
+    JumpTooLarge,
+    #[error("loader error: {0}")]
+    LoaderError(String),
+    #[error("'break' outside of a loop")]
+    BreakOutsideLoop,
+    #[error("'continue' outside of a loop")]
+    ContinueOutsideLoop,
+    #[error("failed to disassemble bytecode: {0}")]
+    DisassembleError(String),
+    #[error("multiple compile errors: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Multiple(Vec<CompileError>),
 }
 
-struct VirtualFunction {
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VirtualFunction {
     name: String,
     arity: usize,
     f_type: VirtualFunctionType,
+    bytecode: Vec<u8>,
+    constants: Vec<Constant>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize)]
 enum VirtualFunctionType {
     Function,
     Script,
@@ -49,22 +128,42 @@ struct Local {
 #[derive(Debug)]
 enum Operation {
     Return,
-    LoadString(String),
-    LoadFloat64(f64),
+    LoadConst(usize),
     Bool(bool),
     Pop,
     Null,
     Undefined,
     Regex(String, String),
-    StoreVar(String),
-    LoadVar(String),
+    StoreVar(usize),
+    LoadVar(usize),
+    GetLocal(usize),
+    SetLocal(usize),
+    Jump(usize),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    Loop(usize),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    Neq,
+    Lt,
+    Gt,
+    Lte,
+    Gte,
+    Neg,
+    Not,
+    Closure(usize),
+    Call(usize),
+    Import(usize),
 }
 
 impl Operation {
     fn get_opcode(&self) -> u8 {
         match self {
-            Operation::LoadString(_) => 0x01,
-            Operation::LoadFloat64(_) => 0x02,
+            Operation::LoadConst(_) => 0x01,
             Operation::Bool(_) => 0x03,
             Operation::Pop => 0x04,
             Operation::Null => 0x05,
@@ -73,14 +172,35 @@ impl Operation {
             Operation::Return => 0x08,
             Operation::StoreVar(_) => 0x09,
             Operation::LoadVar(_) => 0x0a,
+            Operation::Jump(_) => 0x0b,
+            Operation::JumpIfFalse(_) => 0x0c,
+            Operation::Loop(_) => 0x0d,
+            Operation::JumpIfTrue(_) => 0x0e,
+            Operation::Add => 0x0f,
+            Operation::Sub => 0x10,
+            Operation::Mul => 0x11,
+            Operation::Div => 0x12,
+            Operation::Mod => 0x13,
+            Operation::Eq => 0x14,
+            Operation::Neq => 0x15,
+            Operation::Lt => 0x16,
+            Operation::Gt => 0x17,
+            Operation::Lte => 0x18,
+            Operation::Gte => 0x19,
+            Operation::Neg => 0x1a,
+            Operation::Not => 0x1b,
+            Operation::GetLocal(_) => 0x1c,
+            Operation::SetLocal(_) => 0x1d,
+            Operation::Closure(_) => 0x1e,
+            Operation::Call(_) => 0x1f,
+            Operation::Import(_) => 0x20,
         }
     }
 
     fn get_name(&self) -> &'static str {
         match self {
             Operation::Return => "OP_RETURN",
-            Operation::LoadString(_) => "OP_LOAD_STRING",
-            Operation::LoadFloat64(_) => "OP_LOAD_FLOAT64",
+            Operation::LoadConst(_) => "OP_LOAD_CONST",
             Operation::Bool(_) => "OP_LOAD_BOOL",
             Operation::Pop => "OP_POP",
             Operation::Null => "OP_NULL",
@@ -88,8 +208,160 @@ impl Operation {
             Operation::Regex(_, _) => "OP_REGEX",
             Operation::StoreVar(_) => "OP_STORE_VAR",
             Operation::LoadVar(_) => "OP_LOAD_VAR",
+            Operation::Jump(_) => "OP_JUMP",
+            Operation::JumpIfFalse(_) => "OP_JUMP_IF_FALSE",
+            Operation::Loop(_) => "OP_LOOP",
+            Operation::JumpIfTrue(_) => "OP_JUMP_IF_TRUE",
+            Operation::Add => "OP_ADD",
+            Operation::Sub => "OP_SUB",
+            Operation::Mul => "OP_MUL",
+            Operation::Div => "OP_DIV",
+            Operation::Mod => "OP_MOD",
+            Operation::Eq => "OP_EQ",
+            Operation::Neq => "OP_NEQ",
+            Operation::Lt => "OP_LT",
+            Operation::Gt => "OP_GT",
+            Operation::Lte => "OP_LTE",
+            Operation::Gte => "OP_GTE",
+            Operation::Neg => "OP_NEG",
+            Operation::Not => "OP_NOT",
+            Operation::GetLocal(_) => "OP_GET_LOCAL",
+            Operation::SetLocal(_) => "OP_SET_LOCAL",
+            Operation::Closure(_) => "OP_CLOSURE",
+            Operation::Call(_) => "OP_CALL",
+            Operation::Import(_) => "OP_IMPORT",
         }
     }
+
+    // one placeholder instance of every variant (operand values are never
+    // read), used only so decode_opcode can look a raw byte back up against
+    // get_opcode/get_name instead of hand-duplicating their mapping a third
+    // time
+    fn variants() -> [Operation; 31] {
+        [
+            Operation::Return,
+            Operation::LoadConst(0),
+            Operation::Bool(false),
+            Operation::Pop,
+            Operation::Null,
+            Operation::Undefined,
+            Operation::Regex(String::new(), String::new()),
+            Operation::StoreVar(0),
+            Operation::LoadVar(0),
+            Operation::Jump(0),
+            Operation::JumpIfFalse(0),
+            Operation::Loop(0),
+            Operation::JumpIfTrue(0),
+            Operation::Add,
+            Operation::Sub,
+            Operation::Mul,
+            Operation::Div,
+            Operation::Mod,
+            Operation::Eq,
+            Operation::Neq,
+            Operation::Lt,
+            Operation::Gt,
+            Operation::Lte,
+            Operation::Gte,
+            Operation::Neg,
+            Operation::Not,
+            Operation::GetLocal(0),
+            Operation::SetLocal(0),
+            Operation::Closure(0),
+            Operation::Call(0),
+            Operation::Import(0),
+        ]
+    }
+
+    // the operand shape a variant's opcode is followed by on the wire - not
+    // derivable from get_opcode/get_name since neither encodes operand shape
+    fn operand_kind(&self) -> OperandKind {
+        match self {
+            Operation::Bool(_) => OperandKind::Bool,
+            Operation::Regex(_, _) => OperandKind::TwoStrings,
+            Operation::LoadConst(_)
+            | Operation::StoreVar(_)
+            | Operation::LoadVar(_)
+            | Operation::Jump(_)
+            | Operation::JumpIfFalse(_)
+            | Operation::JumpIfTrue(_)
+            | Operation::Loop(_)
+            | Operation::GetLocal(_)
+            | Operation::SetLocal(_)
+            | Operation::Closure(_)
+            | Operation::Call(_)
+            | Operation::Import(_) => OperandKind::U16,
+            Operation::Return
+            | Operation::Pop
+            | Operation::Null
+            | Operation::Undefined
+            | Operation::Add
+            | Operation::Sub
+            | Operation::Mul
+            | Operation::Div
+            | Operation::Mod
+            | Operation::Eq
+            | Operation::Neq
+            | Operation::Lt
+            | Operation::Gt
+            | Operation::Lte
+            | Operation::Gte
+            | Operation::Neg
+            | Operation::Not => OperandKind::None,
+        }
+    }
+
+    // decodes a raw opcode byte by looking it up against get_opcode/get_name,
+    // rather than hand-duplicating their mapping in a third match statement
+    fn decode_opcode(opcode: u8) -> Option<(&'static str, OperandKind, bool)> {
+        Self::variants()
+            .into_iter()
+            .find(|op| op.get_opcode() == opcode)
+            .map(|op| (op.get_name(), op.operand_kind(), op.refers_to_constant()))
+    }
+
+    // whether this variant's U16 operand is a constant pool index, as
+    // opposed to an argument count (Call) or a loader-assigned FileId
+    // (Import) - used by `disassemble` to decide when to resolve the
+    // operand against the constant pool for display
+    fn refers_to_constant(&self) -> bool {
+        matches!(
+            self,
+            Operation::LoadConst(_)
+                | Operation::Closure(_)
+                | Operation::StoreVar(_)
+                | Operation::LoadVar(_)
+        )
+    }
+}
+
+enum OperandKind {
+    None,
+    Bool,
+    U16,
+    TwoStrings,
+}
+
+// renders the constant pool entry at `idx` the way a disassembly listing
+// should show it, e.g. `"hello"` for a string or `<fn add>` for a function
+fn describe_constant(constants: &[Constant], idx: usize) -> String {
+    match constants.get(idx) {
+        Some(Constant::String(s)) => format!("{s:?}"),
+        Some(Constant::Float64(n)) => n.to_string(),
+        Some(Constant::Regex(exp, flags)) => format!("/{exp}/{flags}"),
+        Some(Constant::Function(f)) => format!("<fn {}>", f.name),
+        Some(Constant::Embed(bytes)) => format!("<embed {} bytes>", bytes.len()),
+        None => "<invalid constant index>".to_string(),
+    }
+}
+
+fn read_cstr(bytecode: &[u8], start: usize) -> Result<(String, usize), CompileError> {
+    let len = bytecode[start..]
+        .iter()
+        .position(|&b| b == 0x00)
+        .ok_or_else(|| CompileError::DisassembleError(format!("unterminated string at offset {start}")))?;
+    let s = String::from_utf8_lossy(&bytecode[start..start + len]).into_owned();
+    Ok((s, start + len + 1))
 }
 
 const LOCAL_REPEAT_VALUE: Option<Local> = None;
@@ -98,26 +370,174 @@ impl<'a> Compiler<'a> {
     pub fn new() -> Self {
         Compiler {
             bytecode: Vec::new(),
+            constants: Vec::new(),
             local_count: 0,
             locals: [LOCAL_REPEAT_VALUE; 256],
-            scope: HashMap::new(),
             current_scope_depth: 0,
             enclosing: None,
+            base_dir: PathBuf::from("."),
+            resolved_imports: HashMap::new(),
+            pending_modules: Vec::new(),
+            errors: Vec::new(),
+            loops: Vec::new(),
         }
     }
 
     fn new_enclosing(&'a self) -> Compiler<'a> {
         Compiler {
             bytecode: Vec::new(),
-            scope: HashMap::new(),
+            constants: Vec::new(),
             local_count: 0,
             locals: [LOCAL_REPEAT_VALUE; 256],
             current_scope_depth: self.current_scope_depth + 1,
-            enclosing: None,
+            enclosing: Some(self),
+            base_dir: self.base_dir.clone(),
+            resolved_imports: HashMap::new(),
+            pending_modules: Vec::new(),
+            errors: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    // compiles a full module graph starting at `entry_path`, calling `loader`
+    // to resolve every `import`/embed specifier the graph encounters
+    pub fn compile_program(
+        entry_path: &Path,
+        mut loader: impl FnMut(&str, &Path, FileKind) -> Result<FileId, CompileError>,
+    ) -> Result<Program, CompileError> {
+        let entry_id: FileId = 0;
+        let mut units = HashMap::new();
+        let mut queue = vec![(entry_id, entry_path.to_path_buf())];
+        let mut enqueued: HashSet<FileId> = HashSet::new();
+        enqueued.insert(entry_id);
+
+        while let Some((file_id, path)) = queue.pop() {
+            let mut compiler = Compiler::new();
+            let unit = compiler.compile_file_with_loader(&path, &mut loader)?;
+            for dependency in std::mem::take(&mut compiler.pending_modules) {
+                if enqueued.insert(dependency.0) {
+                    queue.push(dependency);
+                }
+            }
+            units.insert(file_id, unit);
         }
+
+        Ok(Program {
+            entry: entry_id,
+            units,
+        })
     }
 
-    pub fn compile_file(&mut self, path: &Path) -> Result<Vec<u8>, CompileError> {
+    // walks `module`'s top-level `import` declarations up front, resolving
+    // each specifier through `loader` exactly once - this keeps the loader
+    // closure as a plain stack-local borrow instead of a struct field, so it
+    // never has to share a lifetime with the self-referential `enclosing`
+    // chain
+    fn resolve_imports(
+        module: &Module,
+        base_dir: &Path,
+        loader: &mut Loader<'_>,
+    ) -> Result<HashMap<String, (FileId, FileKind)>, CompileError> {
+        let mut resolved = HashMap::new();
+        for item in &module.body {
+            let import_decl = match item {
+                ModuleItem::ModuleDecl(ModuleDecl::Import(import_decl)) => import_decl,
+                _ => continue,
+            };
+            let specifier = import_decl.src.value.to_string();
+            if resolved.contains_key(&specifier) {
+                continue;
+            }
+            let resolved_path = base_dir.join(&specifier);
+            let kind = if specifier.ends_with(".js") || specifier.ends_with(".ts") {
+                FileKind::Module
+            } else {
+                FileKind::Embed
+            };
+            let file_id = loader(&specifier, &resolved_path, kind)?;
+            resolved.insert(specifier, (file_id, kind));
+        }
+        Ok(resolved)
+    }
+
+    // decodes a serialized bytecode stream back into an annotated listing,
+    // verifying that encoding and decoding agree with each other - `constants`
+    // is the pool the bytecode was compiled against, so OP_LOAD_CONST,
+    // OP_CLOSURE, OP_LOAD_VAR and OP_STORE_VAR operands can be shown as the
+    // value they reference instead of a bare pool index
+    pub fn disassemble(bytecode: &[u8], constants: &[Constant]) -> Result<String, CompileError> {
+        let mut out = String::new();
+        let mut offset = 0usize;
+
+        while offset < bytecode.len() {
+            let start = offset;
+            let opcode = bytecode[offset];
+            offset += 1;
+
+            let (name, kind, refers_to_constant) = Operation::decode_opcode(opcode).ok_or_else(|| {
+                CompileError::DisassembleError(format!("unknown opcode 0x{opcode:02x} at offset {start}"))
+            })?;
+
+            let operand = match kind {
+                OperandKind::None => String::new(),
+                OperandKind::Bool => {
+                    let byte = *bytecode.get(offset).ok_or_else(|| {
+                        CompileError::DisassembleError(format!("truncated operand at offset {start}"))
+                    })?;
+                    offset += 1;
+                    format!(" {}", byte != 0)
+                }
+                OperandKind::U16 => {
+                    let bytes = bytecode.get(offset..offset + 2).ok_or_else(|| {
+                        CompileError::DisassembleError(format!("truncated operand at offset {start}"))
+                    })?;
+                    let value = u16::from_be_bytes([bytes[0], bytes[1]]);
+                    offset += 2;
+                    if refers_to_constant {
+                        format!(" {value} {}", describe_constant(constants, value as usize))
+                    } else {
+                        format!(" {value}")
+                    }
+                }
+                OperandKind::TwoStrings => {
+                    let (exp, next) = read_cstr(bytecode, offset)?;
+                    let (flags, next) = read_cstr(bytecode, next)?;
+                    offset = next;
+                    format!(" {exp:?} {flags:?}")
+                }
+            };
+
+            out.push_str(&format!("{start:04} {name}{operand}\n"));
+        }
+
+        Ok(out)
+    }
+
+    pub fn compile_file(&mut self, path: &Path) -> Result<CompiledUnit, CompileError> {
+        self.compile_file_impl(path, None)
+    }
+
+    // same as `compile_file`, but resolves `import`/embed specifiers found in
+    // the file through `loader` instead of reporting each one as a
+    // `LoaderError`
+    pub fn compile_file_with_loader(
+        &mut self,
+        path: &Path,
+        loader: &mut Loader<'_>,
+    ) -> Result<CompiledUnit, CompileError> {
+        self.compile_file_impl(path, Some(loader))
+    }
+
+    fn compile_file_impl(
+        &mut self,
+        path: &Path,
+        loader: Option<&mut Loader<'_>>,
+    ) -> Result<CompiledUnit, CompileError> {
+        self.base_dir = path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+
         let cm: Lrc<SourceMap> = Default::default();
 
         let handler = Handler::with_tty_emitter(ColorConfig::Auto, true, false, Some(cm.clone()));
@@ -152,14 +572,36 @@ impl<'a> Compiler<'a> {
 
         println!("parsing took {:?}", Instant::now() - parse_start);
 
-        Ok(self.compile(&module))
+        self.resolved_imports = match loader {
+            Some(loader) => Self::resolve_imports(&module, &self.base_dir, loader)?,
+            None => HashMap::new(),
+        };
+
+        self.compile(&module)
     }
 
-    pub fn compile(&mut self, module: &Module) -> Vec<u8> {
+    pub fn compile(&mut self, module: &Module) -> Result<CompiledUnit, CompileError> {
         let compile_start = Instant::now();
         module.visit_with(self);
         println!("compiling took {:?}", Instant::now() - compile_start);
-        self.bytecode.clone()
+        let mut errors: Vec<_> = self.errors.drain(..).collect();
+        match errors.len() {
+            0 => {}
+            1 => return Err(errors.pop().unwrap()),
+            _ => return Err(CompileError::Multiple(errors)),
+        }
+        Ok(CompiledUnit {
+            bytecode: self.bytecode.clone(),
+            constants: self.constants.clone(),
+        })
+    }
+
+    // runs a read-only lint pass over `module`, independent of bytecode
+    // generation, so callers can reject or warn on a script before compiling it
+    pub fn analyze(module: &Module) -> Vec<Diagnostic> {
+        let mut analyzer = Analyzer::new();
+        module.visit_with(&mut analyzer);
+        analyzer.diagnostics
     }
 
     fn enter_scope(&mut self) {
@@ -168,11 +610,54 @@ impl<'a> Compiler<'a> {
 
     fn exit_scope(&mut self) {
         self.current_scope_depth -= 1;
+        while self.local_count > 0 {
+            let still_in_scope = match &self.locals[self.local_count - 1] {
+                Some(local) => local.depth <= self.current_scope_depth,
+                None => true,
+            };
+            if still_in_scope {
+                break;
+            }
+            self.emit_op(Operation::Pop);
+            self.local_count -= 1;
+        }
+    }
+
+    // emits a `Pop` for every local deeper than `target_depth`, without
+    // releasing their compile-time slots - unlike `exit_scope`, this runs on
+    // a `break`/`continue` jump that bypasses the scopes it would normally
+    // unwind, so the slots are still live for the code that follows
+    fn emit_scope_unwind(&mut self, target_depth: usize) {
+        for i in (0..self.local_count).rev() {
+            let depth = match &self.locals[i] {
+                Some(local) => local.depth,
+                None => 0,
+            };
+            if depth <= target_depth {
+                break;
+            }
+            self.emit_op(Operation::Pop);
+        }
+    }
+
+    fn enter_loop(&mut self, depth: usize) {
+        self.loops.push(LoopContext {
+            depth,
+            break_jumps: Vec::new(),
+            continue_jumps: Vec::new(),
+        });
+    }
+
+    fn exit_loop(&mut self) -> LoopContext {
+        self.loops
+            .pop()
+            .expect("exit_loop called without a matching enter_loop")
     }
 
     fn add_local(&mut self, name: String, depth: usize) {
         if self.local_count >= 256 {
-            todo!("too many locals, max number of locals supported is 256");
+            self.errors.push(CompileError::TooManyLocals);
+            return;
         }
         self.locals[self.local_count as usize] = Some(Local {
             name,
@@ -181,18 +666,28 @@ impl<'a> Compiler<'a> {
         self.local_count += 1;
     }
 
-    fn declare_variable(&mut self, name: String) {
-        self.scope.insert(name, self.current_scope_depth);
+    // resolves `name` to a local slot, searching innermost-scope-first so
+    // shadowing works; returns None when it must be a global instead
+    fn resolve_variable(&self, name: &str) -> Option<usize> {
+        for i in (0..self.local_count).rev() {
+            if let Some(local) = &self.locals[i] {
+                if local.name == name && local.depth <= self.current_scope_depth {
+                    return Some(i);
+                }
+            }
+        }
+        None
     }
 
-    // fn resolve_variable(&self, name: &str) -> Option<usize> {
-    //     for (i, scope) in self.scopes.iter().enumerate().rev() {
-    //         if let Some(depth) = scope.get(name) {
-    //             return Some(self.current_scope_depth - i);
-    //         }
-    //     }
-    //     None
-    // }
+    // interns `constant` in the pool, reusing an existing entry with the same
+    // value instead of duplicating it
+    fn add_constant(&mut self, constant: Constant) -> usize {
+        if let Some(idx) = self.constants.iter().position(|c| c == &constant) {
+            return idx;
+        }
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
 
     // compile variable declarations
     fn compile_var_decl(&mut self, var_decl: &VarDecl) {
@@ -203,14 +698,20 @@ impl<'a> Compiler<'a> {
 
     fn compile_var_declator(&mut self, var_declator: &VarDeclarator) {
         match var_declator.init {
-            Some(ref init) => self.compile_expr(init),
+            Some(ref init) => self.compile_expr_value(init),
             None => self.emit_op(Operation::Undefined),
         }
         match &var_declator.name {
             Pat::Ident(name) => {
-                println!("{:?}", name.id.sym.to_string());
-                self.declare_variable(name.id.sym.to_string());
-                self.emit_op(Operation::StoreVar(name.id.to_string()));
+                let ident = name.id.sym.to_string();
+                if self.current_scope_depth > 0 {
+                    // the initializer's value is already sitting on top of the
+                    // stack in the slot this local will occupy - no store needed
+                    self.add_local(ident, self.current_scope_depth);
+                } else {
+                    let idx = self.add_constant(Constant::String(ident));
+                    self.emit_op(Operation::StoreVar(idx));
+                }
             }
             Pat::Array(_) => todo!(),
             Pat::Rest(_) => todo!(),
@@ -222,20 +723,189 @@ impl<'a> Compiler<'a> {
     }
 
     fn compile_expr(&mut self, expr: &Expr) {
+        self.compile_expr_value(expr);
+        self.emit_op(Operation::Pop);
+    }
+
+    // compiles an expression for its value, leaving exactly one value on the
+    // stack and never popping it - callers are responsible for discarding it
+    fn compile_expr_value(&mut self, expr: &Expr) {
         match expr {
             Expr::Lit(lit) => self.compile_lit(lit),
+            Expr::Bin(bin) => self.compile_bin_expr(bin),
+            Expr::Unary(unary) => self.compile_unary_expr(unary),
+            Expr::Ident(ident) => self.compile_ident(ident),
+            Expr::Call(call) => self.compile_call_expr(call),
+            Expr::Update(update) => self.compile_update_expr(update),
             _ => unimplemented!(),
         }
-        self.emit_op(Operation::Pop);
+    }
+
+    // compiles `++i`/`i++`/`--i`/`i--`, reading the current value, storing the
+    // incremented/decremented value back, and leaving the pre- or post-update
+    // value on the stack depending on `prefix` - only a bare identifier target
+    // is supported, matching compile_ident's "local slot or global name" model
+    fn compile_update_expr(&mut self, update: &UpdateExpr) {
+        let name = match &*update.arg {
+            Expr::Ident(ident) => ident.sym.to_string(),
+            _ => unimplemented!(),
+        };
+        let delta_idx = self.add_constant(Constant::Float64(1.0));
+        let op = match update.op {
+            UpdateOp::PlusPlus => Operation::Add,
+            UpdateOp::MinusMinus => Operation::Sub,
+        };
+
+        match self.resolve_variable(&name) {
+            Some(slot) => {
+                if !update.prefix {
+                    self.emit_op(Operation::GetLocal(slot));
+                }
+                self.emit_op(Operation::GetLocal(slot));
+                self.emit_op(Operation::LoadConst(delta_idx));
+                self.emit_op(op);
+                self.emit_op(Operation::SetLocal(slot));
+                if update.prefix {
+                    self.emit_op(Operation::GetLocal(slot));
+                }
+            }
+            None => {
+                let idx = self.add_constant(Constant::String(name));
+                if !update.prefix {
+                    self.emit_op(Operation::LoadVar(idx));
+                }
+                self.emit_op(Operation::LoadVar(idx));
+                self.emit_op(Operation::LoadConst(delta_idx));
+                self.emit_op(op);
+                self.emit_op(Operation::StoreVar(idx));
+                if update.prefix {
+                    self.emit_op(Operation::LoadVar(idx));
+                }
+            }
+        }
+    }
+
+    fn compile_call_expr(&mut self, call: &CallExpr) {
+        match &call.callee {
+            Callee::Expr(callee) => self.compile_expr_value(callee),
+            _ => unimplemented!(),
+        }
+        for arg in &call.args {
+            if arg.spread.is_some() {
+                unimplemented!();
+            }
+            self.compile_expr_value(&arg.expr);
+        }
+        self.emit_op(Operation::Call(call.args.len()));
+    }
+
+    fn compile_ident(&mut self, ident: &Ident) {
+        let name = ident.sym.to_string();
+        match self.resolve_variable(&name) {
+            Some(slot) => self.emit_op(Operation::GetLocal(slot)),
+            None => {
+                let idx = self.add_constant(Constant::String(name));
+                self.emit_op(Operation::LoadVar(idx));
+            }
+        }
+    }
+
+    fn compile_bin_expr(&mut self, bin: &BinExpr) {
+        match bin.op {
+            BinaryOp::LogicalAnd => {
+                self.compile_expr_value(&bin.left);
+                let end_jump = self.emit_jump(Operation::JumpIfFalse);
+                self.emit_op(Operation::Pop);
+                self.compile_expr_value(&bin.right);
+                self.patch_jump(end_jump);
+                return;
+            }
+            BinaryOp::LogicalOr => {
+                self.compile_expr_value(&bin.left);
+                let end_jump = self.emit_jump(Operation::JumpIfTrue);
+                self.emit_op(Operation::Pop);
+                self.compile_expr_value(&bin.right);
+                self.patch_jump(end_jump);
+                return;
+            }
+            _ => {}
+        }
+
+        self.compile_expr_value(&bin.left);
+        self.compile_expr_value(&bin.right);
+        match bin.op {
+            BinaryOp::Add => self.emit_op(Operation::Add),
+            BinaryOp::Sub => self.emit_op(Operation::Sub),
+            BinaryOp::Mul => self.emit_op(Operation::Mul),
+            BinaryOp::Div => self.emit_op(Operation::Div),
+            BinaryOp::Mod => self.emit_op(Operation::Mod),
+            BinaryOp::EqEq | BinaryOp::EqEqEq => self.emit_op(Operation::Eq),
+            BinaryOp::NotEq | BinaryOp::NotEqEq => self.emit_op(Operation::Neq),
+            BinaryOp::Lt => self.emit_op(Operation::Lt),
+            BinaryOp::Gt => self.emit_op(Operation::Gt),
+            BinaryOp::LtEq => self.emit_op(Operation::Lte),
+            BinaryOp::GtEq => self.emit_op(Operation::Gte),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn compile_unary_expr(&mut self, unary: &UnaryExpr) {
+        self.compile_expr_value(&unary.arg);
+        match unary.op {
+            UnaryOp::Minus => self.emit_op(Operation::Neg),
+            UnaryOp::Bang => self.emit_op(Operation::Not),
+            _ => unimplemented!(),
+        }
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) {
+        stmt.visit_with(self);
+    }
+
+    // emits `op` with a placeholder 2-byte operand and returns the byte
+    // offset of that placeholder so it can be backpatched once the jump
+    // target is known
+    fn emit_jump(&mut self, op: fn(usize) -> Operation) -> usize {
+        self.emit_op(op(0xffff));
+        self.bytecode.len() - 2
+    }
+
+    // backpatches the placeholder operand at `offset` with the distance from
+    // the end of the jump instruction to the current end of the bytecode
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.bytecode.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            self.errors.push(CompileError::JumpTooLarge);
+            return;
+        }
+        let bytes = (jump as u16).to_be_bytes();
+        self.bytecode[offset] = bytes[0];
+        self.bytecode[offset + 1] = bytes[1];
+    }
+
+    // emits a backward `Loop` jump to `loop_start`
+    fn emit_loop(&mut self, loop_start: usize) {
+        // +3 accounts for the Loop instruction itself (1 opcode byte + 2
+        // operand bytes), which isn't in `self.bytecode` yet at this point -
+        // the VM computes `ip - offset` from the opcode *after* reading it,
+        // so the offset must already include its own width
+        let offset = self.bytecode.len() - loop_start + 3;
+        if offset > u16::MAX as usize {
+            self.errors.push(CompileError::JumpTooLarge);
+            return;
+        }
+        self.emit_op(Operation::Loop(offset));
     }
 
     fn compile_lit(&mut self, lit: &Lit) {
         match lit {
             Lit::Str(s) => {
-                self.emit_op(Operation::LoadString(s.value.to_string().clone()));
+                let idx = self.add_constant(Constant::String(s.value.to_string()));
+                self.emit_op(Operation::LoadConst(idx));
             }
             Lit::Num(n) => {
-                self.emit_op(Operation::LoadFloat64(n.value));
+                let idx = self.add_constant(Constant::Float64(n.value));
+                self.emit_op(Operation::LoadConst(idx));
             }
             Lit::Bool(b) => {
                 self.emit_op(Operation::Bool(b.value));
@@ -257,13 +927,6 @@ impl<'a> Compiler<'a> {
     fn emit_op(&mut self, op: Operation) {
         self.bytecode.push(op.get_opcode());
         match op {
-            Operation::LoadString(s) => {
-                self.emit_string(&s);
-            }
-            Operation::LoadFloat64(n) => {
-                let bytes = n.to_le_bytes();
-                self.bytecode.extend_from_slice(&bytes);
-            }
             Operation::Bool(b) => {
                 if b {
                     self.bytecode.push(0x01);
@@ -271,9 +934,6 @@ impl<'a> Compiler<'a> {
                     self.bytecode.push(0x00);
                 }
             }
-            Operation::StoreVar(name) => {
-                self.emit_string(&name);
-            }
             Operation::Regex(exp, flags) => {
                 self.emit_string(&exp);
                 self.emit_string(&flags);
@@ -282,7 +942,35 @@ impl<'a> Compiler<'a> {
             Operation::Undefined => {}
             Operation::Pop => {}
             Operation::Null => {}
-            Operation::LoadVar(_) => todo!(),
+            Operation::LoadConst(idx)
+            | Operation::StoreVar(idx)
+            | Operation::LoadVar(idx)
+            | Operation::GetLocal(idx)
+            | Operation::SetLocal(idx)
+            | Operation::Closure(idx)
+            | Operation::Call(idx)
+            | Operation::Import(idx) => {
+                self.bytecode.extend_from_slice(&(idx as u16).to_be_bytes());
+            }
+            Operation::Jump(offset)
+            | Operation::JumpIfFalse(offset)
+            | Operation::JumpIfTrue(offset)
+            | Operation::Loop(offset) => {
+                self.bytecode.extend_from_slice(&(offset as u16).to_be_bytes());
+            }
+            Operation::Add => {}
+            Operation::Sub => {}
+            Operation::Mul => {}
+            Operation::Div => {}
+            Operation::Mod => {}
+            Operation::Eq => {}
+            Operation::Neq => {}
+            Operation::Lt => {}
+            Operation::Gt => {}
+            Operation::Lte => {}
+            Operation::Gte => {}
+            Operation::Neg => {}
+            Operation::Not => {}
         }
     }
 
@@ -294,16 +982,911 @@ impl<'a> Compiler<'a> {
 
 impl Visit for Compiler<'_> {
     fn visit_expr(&mut self, expr: &Expr) {
+        // compile_expr (via compile_expr_value) already walks any sub-expressions
+        // itself, so we must not also let the default visitor descend into them
         self.compile_expr(expr);
-        expr.visit_children_with(self);
     }
     fn visit_var_decl(&mut self, n: &VarDecl) {
+        // compile_var_decl (via compile_expr_value) already compiles each
+        // declarator's initializer itself, so we must not also let the
+        // default visitor re-walk it - same reasoning as visit_expr above
         self.compile_var_decl(n);
-        n.visit_children_with(self);
     }
-    fn visit_fn_decl(&mut self,n: &FnDecl) {
+    fn visit_fn_decl(&mut self, n: &FnDecl) {
+        let name = n.ident.sym.to_string();
+        let params: Vec<String> = n
+            .function
+            .params
+            .iter()
+            .map(|param| match &param.pat {
+                Pat::Ident(ident) => ident.id.sym.to_string(),
+                _ => todo!(),
+            })
+            .collect();
+        let arity = params.len();
+
+        let (bytecode, constants) = {
+            let mut child = self.new_enclosing();
+            for param in params {
+                child.add_local(param, child.current_scope_depth);
+            }
+            if let Some(body) = &n.function.body {
+                body.visit_children_with(&mut child);
+            }
+            // implicit return for a function that falls off the end
+            child.emit_op(Operation::Undefined);
+            child.emit_op(Operation::Return);
+            (child.bytecode, child.constants)
+        };
+
+        let function = VirtualFunction {
+            name: name.clone(),
+            arity,
+            f_type: VirtualFunctionType::Function,
+            bytecode,
+            constants,
+        };
+        let const_idx = self.add_constant(Constant::Function(function));
+        self.emit_op(Operation::Closure(const_idx));
+
+        if self.current_scope_depth > 0 {
+            self.add_local(name, self.current_scope_depth);
+        } else {
+            let name_idx = self.add_constant(Constant::String(name));
+            self.emit_op(Operation::StoreVar(name_idx));
+        }
+    }
+
+    fn visit_import_decl(&mut self, n: &ImportDecl) {
+        let specifier = n.src.value.to_string();
+
+        let (file_id, kind) = match self.resolved_imports.get(&specifier) {
+            Some(&resolved) => resolved,
+            None => {
+                self.errors.push(CompileError::LoaderError(format!(
+                    "no loader configured to resolve import {specifier:?}"
+                )));
+                return;
+            }
+        };
+        let resolved_path = self.base_dir.join(&specifier);
+
+        match kind {
+            FileKind::Module => {
+                self.pending_modules.push((file_id, resolved_path));
+                self.emit_op(Operation::Import(file_id));
+            }
+            FileKind::Embed => match std::fs::read(&resolved_path) {
+                Ok(bytes) => {
+                    let idx = self.add_constant(Constant::Embed(bytes));
+                    self.emit_op(Operation::LoadConst(idx));
+                }
+                Err(e) => self.errors.push(CompileError::LoaderError(e.to_string())),
+            },
+        }
+    }
+
+    fn visit_return_stmt(&mut self, n: &ReturnStmt) {
+        match &n.arg {
+            Some(expr) => self.compile_expr_value(expr),
+            None => self.emit_op(Operation::Undefined),
+        }
+        self.emit_op(Operation::Return);
+    }
+
+    fn visit_if_stmt(&mut self, n: &IfStmt) {
+        self.compile_expr_value(&n.test);
+        let then_jump = self.emit_jump(Operation::JumpIfFalse);
+        self.emit_op(Operation::Pop);
         self.enter_scope();
-        n.visit_children_with(self);
+        self.compile_stmt(&n.cons);
+        self.exit_scope();
+
+        let else_jump = self.emit_jump(Operation::Jump);
+        self.patch_jump(then_jump);
+        self.emit_op(Operation::Pop);
+
+        if let Some(alt) = &n.alt {
+            self.enter_scope();
+            self.compile_stmt(alt);
+            self.exit_scope();
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn visit_while_stmt(&mut self, n: &WhileStmt) {
+        let loop_start = self.bytecode.len();
+        self.compile_expr_value(&n.test);
+        let exit_jump = self.emit_jump(Operation::JumpIfFalse);
+        self.emit_op(Operation::Pop);
+
+        self.enter_loop(self.current_scope_depth);
+        self.enter_scope();
+        self.compile_stmt(&n.body);
         self.exit_scope();
+        let loop_ctx = self.exit_loop();
+        for continue_jump in loop_ctx.continue_jumps {
+            self.patch_jump(continue_jump);
+        }
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit_op(Operation::Pop);
+        for break_jump in loop_ctx.break_jumps {
+            self.patch_jump(break_jump);
+        }
+    }
+
+    fn visit_for_stmt(&mut self, n: &ForStmt) {
+        self.enter_scope();
+
+        if let Some(init) = &n.init {
+            match init {
+                VarDeclOrExpr::VarDecl(var_decl) => self.compile_var_decl(var_decl),
+                VarDeclOrExpr::Expr(expr) => self.compile_expr(expr),
+            }
+        }
+
+        let loop_start = self.bytecode.len();
+        let mut exit_jump = None;
+        if let Some(test) = &n.test {
+            self.compile_expr_value(test);
+            exit_jump = Some(self.emit_jump(Operation::JumpIfFalse));
+            self.emit_op(Operation::Pop);
+        }
+
+        self.enter_loop(self.current_scope_depth);
+        self.enter_scope();
+        self.compile_stmt(&n.body);
+        self.exit_scope();
+        let loop_ctx = self.exit_loop();
+        for continue_jump in loop_ctx.continue_jumps {
+            self.patch_jump(continue_jump);
+        }
+
+        if let Some(update) = &n.update {
+            self.compile_expr(update);
+        }
+        self.emit_loop(loop_start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+            self.emit_op(Operation::Pop);
+        }
+        for break_jump in loop_ctx.break_jumps {
+            self.patch_jump(break_jump);
+        }
+
+        self.exit_scope();
+    }
+
+    fn visit_break_stmt(&mut self, n: &BreakStmt) {
+        if n.label.is_some() {
+            unimplemented!("labeled break is not supported");
+        }
+        let target_depth = match self.loops.last() {
+            Some(loop_ctx) => loop_ctx.depth,
+            None => {
+                self.errors.push(CompileError::BreakOutsideLoop);
+                return;
+            }
+        };
+        self.emit_scope_unwind(target_depth);
+        let jump = self.emit_jump(Operation::Jump);
+        self.loops.last_mut().unwrap().break_jumps.push(jump);
+    }
+
+    fn visit_continue_stmt(&mut self, n: &ContinueStmt) {
+        if n.label.is_some() {
+            unimplemented!("labeled continue is not supported");
+        }
+        let target_depth = match self.loops.last() {
+            Some(loop_ctx) => loop_ctx.depth,
+            None => {
+                self.errors.push(CompileError::ContinueOutsideLoop);
+                return;
+            }
+        };
+        self.emit_scope_unwind(target_depth);
+        let jump = self.emit_jump(Operation::Jump);
+        self.loops.last_mut().unwrap().continue_jumps.push(jump);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub severity: Severity,
+    pub span: Span,
+}
+
+struct Binding {
+    span: Span,
+    used: bool,
+}
+
+// a read-only companion to `Compiler` that walks the same AST looking for
+// lint-worthy patterns instead of emitting bytecode
+struct Analyzer {
+    diagnostics: Vec<Diagnostic>,
+    // one binding map per currently open block scope, innermost last, so
+    // same-named bindings in sibling or nested scopes don't collide
+    scopes: Vec<HashMap<String, Binding>>,
+    // names declared by a `let`/`const` further down *some* currently open
+    // block that are not yet in scope - one entry per open block, same
+    // indexing as `scopes` - reading one before it's reached is an error
+    // even from a nested block, so `is_pending` checks every open frame
+    tdz_stack: Vec<HashSet<String>>,
+}
+
+impl Analyzer {
+    fn new() -> Self {
+        Analyzer {
+            diagnostics: Vec::new(),
+            scopes: Vec::new(),
+            tdz_stack: Vec::new(),
+        }
+    }
+
+    // marks the nearest enclosing declaration of `name` as used, innermost
+    // scope first, so shadowed outer bindings aren't mistakenly credited
+    fn mark_used(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.get_mut(name) {
+                binding.used = true;
+                return;
+            }
+        }
+    }
+
+    fn is_pending(&self, name: &str) -> bool {
+        self.tdz_stack.iter().any(|pending| pending.contains(name))
+    }
+
+    // pops the innermost scope and reports any binding in it that was never
+    // read
+    fn pop_scope(&mut self) {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return,
+        };
+        for (name, binding) in scope {
+            if !binding.used {
+                self.diagnostics.push(Diagnostic {
+                    message: format!("unused variable `{name}`"),
+                    severity: Severity::Warning,
+                    span: binding.span,
+                });
+            }
+        }
+    }
+
+    fn pending_names(stmts: &[Stmt]) -> HashSet<String> {
+        let mut pending = HashSet::new();
+        for stmt in stmts {
+            if let Stmt::Decl(Decl::Var(var_decl)) = stmt {
+                if matches!(var_decl.kind, VarDeclKind::Let | VarDeclKind::Const) {
+                    for decl in &var_decl.decls {
+                        if let Pat::Ident(ident) = &decl.name {
+                            pending.insert(ident.id.sym.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        pending
+    }
+
+    // flags the first statement reached after an unconditional `return`
+    // within the same statement list
+    fn check_unreachable(&mut self, stmts: &[Stmt]) {
+        let mut seen_return = false;
+        for stmt in stmts {
+            if seen_return {
+                self.diagnostics.push(Diagnostic {
+                    message: "unreachable code after return".into(),
+                    severity: Severity::Warning,
+                    span: stmt.span(),
+                });
+                break;
+            }
+            if matches!(stmt, Stmt::Return(_)) {
+                seen_return = true;
+            }
+        }
+    }
+}
+
+impl Visit for Analyzer {
+    // the module body is its own top-level scope, mirroring `visit_block_stmt`,
+    // so module-level `let`/`const` bindings get unused/TDZ tracking too
+    fn visit_module(&mut self, n: &Module) {
+        let stmts: Vec<Stmt> = n
+            .body
+            .iter()
+            .filter_map(|item| match item {
+                ModuleItem::Stmt(stmt) => Some(stmt.clone()),
+                _ => None,
+            })
+            .collect();
+        self.tdz_stack.push(Self::pending_names(&stmts));
+        self.scopes.push(HashMap::new());
+        n.visit_children_with(self);
+        self.tdz_stack.pop();
+        self.pop_scope();
+    }
+
+    fn visit_block_stmt(&mut self, n: &BlockStmt) {
+        self.check_unreachable(&n.stmts);
+
+        self.tdz_stack.push(Self::pending_names(&n.stmts));
+        self.scopes.push(HashMap::new());
+        n.visit_children_with(self);
+        self.tdz_stack.pop();
+        self.pop_scope();
+    }
+
+    fn visit_var_decl(&mut self, n: &VarDecl) {
+        for decl in &n.decls {
+            if let Some(init) = &decl.init {
+                init.visit_with(self);
+            }
+            if let Pat::Ident(ident) = &decl.name {
+                let name = ident.id.sym.to_string();
+                if let Some(pending) = self.tdz_stack.last_mut() {
+                    pending.remove(&name);
+                }
+                if matches!(n.kind, VarDeclKind::Let | VarDeclKind::Const) {
+                    if let Some(scope) = self.scopes.last_mut() {
+                        scope.insert(
+                            name,
+                            Binding {
+                                span: ident.id.span,
+                                used: false,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn visit_ident(&mut self, n: &Ident) {
+        let name = n.sym.to_string();
+        if self.is_pending(&name) {
+            self.diagnostics.push(Diagnostic {
+                message: format!("`{name}` is used before its declaration"),
+                severity: Severity::Error,
+                span: n.span,
+            });
+        }
+        self.mark_used(&name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // parses `src` as a standalone module, bypassing `compile_file`'s
+    // filesystem access so tests can exercise the compiler on inline source
+    fn parse(src: &str) -> Module {
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(swc_common::FileName::Custom("test.js".into()), src.into());
+        let lexer = Lexer::new(
+            Syntax::Es(Default::default()),
+            Default::default(),
+            StringInput::from(&*fm),
+            None,
+        );
+        let mut parser = Parser::new_from(lexer);
+        parser.parse_module().expect("failed to parse test source")
+    }
+
+    #[test]
+    fn patch_jump_computes_forward_distance() {
+        let mut c = Compiler::new();
+        c.emit_op(Operation::Bool(true));
+        let offset = c.emit_jump(Operation::JumpIfFalse);
+        c.emit_op(Operation::Pop);
+        c.patch_jump(offset);
+
+        let jump = u16::from_be_bytes([c.bytecode[offset], c.bytecode[offset + 1]]) as usize;
+        // one OP_POP byte sits between the end of the placeholder and the
+        // current end of the bytecode
+        assert_eq!(jump, 1);
+    }
+
+    #[test]
+    fn emit_loop_offset_lands_exactly_on_loop_start() {
+        let mut c = Compiler::new();
+        let loop_start = c.bytecode.len();
+        c.emit_op(Operation::Bool(true));
+        c.emit_loop(loop_start);
+
+        // OP_LOOP's operand is written right after its own opcode byte
+        let loop_operand_offset = c.bytecode.len() - 2;
+        let offset = u16::from_be_bytes([
+            c.bytecode[loop_operand_offset],
+            c.bytecode[loop_operand_offset + 1],
+        ]) as usize;
+        // the VM lands at `ip - offset` where ip is the end of the Loop
+        // instruction, which must equal loop_start
+        assert_eq!(c.bytecode.len() - offset, loop_start);
+    }
+
+    #[test]
+    fn patch_jump_reports_compile_error_instead_of_panicking_when_too_large() {
+        let mut c = Compiler::new();
+        let offset = c.emit_jump(Operation::JumpIfFalse);
+        c.bytecode.resize(offset + 2 + u16::MAX as usize + 1, 0);
+        c.patch_jump(offset);
+        assert!(matches!(c.errors.as_slice(), [CompileError::JumpTooLarge]));
+    }
+
+    #[test]
+    fn emit_loop_reports_compile_error_instead_of_panicking_when_too_large() {
+        let mut c = Compiler::new();
+        let loop_start = c.bytecode.len();
+        c.bytecode.resize(u16::MAX as usize + 1, 0);
+        c.emit_loop(loop_start);
+        assert!(matches!(c.errors.as_slice(), [CompileError::JumpTooLarge]));
+    }
+
+    #[test]
+    fn if_else_compiles_reachable_branches_with_patched_jumps() {
+        let module = parse("if (true) { 1; } else { 2; }");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_JUMP_IF_FALSE"));
+        assert!(listing.contains("OP_JUMP "));
+    }
+
+    #[test]
+    fn binary_expr_compiles_operands_before_operator() {
+        let module = parse("1 + 2;");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        let load_count = listing.matches("OP_LOAD_CONST").count();
+        assert_eq!(load_count, 2);
+        assert!(listing.contains("OP_ADD"));
+        // the value is discarded at statement level
+        assert!(listing.contains("OP_POP"));
+    }
+
+    #[test]
+    fn unary_expr_compiles_operand_then_operator() {
+        let module = parse("-1;");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_LOAD_CONST"));
+        assert!(listing.contains("OP_NEG"));
+    }
+
+    #[test]
+    fn logical_and_short_circuits_via_jump_if_false() {
+        let module = parse("true && false;");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_JUMP_IF_FALSE"));
+        // `&&` skips its right side when the left is falsy, so it must not use
+        // `||`'s jump-if-true
+        assert!(!listing.contains("OP_JUMP_IF_TRUE"));
+    }
+
+    #[test]
+    fn logical_or_short_circuits_via_jump_if_true() {
+        let module = parse("true || false;");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_JUMP_IF_TRUE"));
+    }
+
+    #[test]
+    fn var_decl_initializer_with_a_call_is_compiled_exactly_once() {
+        let module = parse("function f() { let x = g(); return x; }");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let function = unit
+            .constants
+            .iter()
+            .find_map(|c| match c {
+                Constant::Function(f) => Some(f),
+                _ => None,
+            })
+            .expect("expected a Function constant");
+        let listing = Compiler::disassemble(&function.bytecode, &function.constants).expect("disassemble failed");
+        assert_eq!(
+            listing.matches("OP_CALL").count(),
+            1,
+            "initializer call should not be visited and compiled twice:\n{listing}"
+        );
+    }
+
+    #[test]
+    fn if_branch_locals_are_popped_so_later_locals_reuse_their_slot() {
+        // only one of the two branches ever runs, so each branch's `let`
+        // must be scoped to it - a variable declared after the if should
+        // land in the slot the (unexecuted) branches reserved and released,
+        // not stacked on top of both of them
+        let module = parse("function f() { if (true) { let a = 1; } else { let b = 2; } let c = 3; return c; }");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let function = unit
+            .constants
+            .iter()
+            .find_map(|c| match c {
+                Constant::Function(f) => Some(f),
+                _ => None,
+            })
+            .expect("expected a Function constant");
+        let listing = Compiler::disassemble(&function.bytecode, &function.constants).expect("disassemble failed");
+        assert!(
+            listing.contains("OP_GET_LOCAL 0"),
+            "expected `c` to resolve to slot 0, got:\n{listing}"
+        );
+    }
+
+    #[test]
+    fn add_constant_deduplicates_equal_values() {
+        let mut c = Compiler::new();
+        let a = c.add_constant(Constant::String("x".into()));
+        let b = c.add_constant(Constant::Float64(1.0));
+        let c_idx = c.add_constant(Constant::String("x".into()));
+        assert_eq!(a, c_idx);
+        assert_ne!(a, b);
+        assert_eq!(c.constants.len(), 2);
+    }
+
+    #[test]
+    fn global_var_decl_resolves_through_constant_pool() {
+        let module = parse("var x = 1;");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        assert!(unit.constants.contains(&Constant::String("x".into())));
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_STORE_VAR"));
+    }
+
+    #[test]
+    fn for_loop_var_resolves_to_local_not_global() {
+        let module = parse("for (var i = 0; i < 1; ) { i; }");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_GET_LOCAL"));
+        assert!(!listing.contains("OP_LOAD_VAR"));
+    }
+
+    #[test]
+    fn for_loop_with_a_real_increment_update_compiles_without_panicking() {
+        let module = parse("for (let i = 0; i < 10; i++) { i; }");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_SET_LOCAL"), "expected i++ to store back through a local slot:\n{listing}");
+    }
+
+    #[test]
+    fn break_jumps_past_the_loop_instead_of_compiling_to_nothing() {
+        let module = parse("function f() { while (true) { break; 1; } return 2; }");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let function = unit
+            .constants
+            .iter()
+            .find_map(|c| match c {
+                Constant::Function(f) => Some(f),
+                _ => None,
+            })
+            .expect("expected a Function constant");
+        let listing = Compiler::disassemble(&function.bytecode, &function.constants).expect("disassemble failed");
+        // the break's OP_JUMP must land after the loop (past OP_LOOP), not
+        // fall through into the unreachable `1;` - i.e. some jump's target
+        // offset must be past the OP_LOOP instruction
+        assert_eq!(
+            listing.matches("OP_JUMP ").count(),
+            1,
+            "expected exactly one unconditional jump for the break:\n{listing}"
+        );
+        let loop_offset = listing
+            .lines()
+            .find(|line| line.contains("OP_LOOP"))
+            .expect("expected an OP_LOOP instruction");
+        let loop_offset: usize = loop_offset
+            .trim_start()
+            .split(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        // OP_JUMP's operand is a forward distance from the end of its own
+        // 3-byte instruction, not an absolute offset
+        let jump_line = listing
+            .lines()
+            .find(|line| line.contains("OP_JUMP "))
+            .unwrap();
+        let jump_offset: usize = jump_line.trim_start().split(' ').next().unwrap().parse().unwrap();
+        let jump_distance: usize = jump_line.rsplit(' ').next().unwrap().parse().unwrap();
+        let jump_target = jump_offset + 3 + jump_distance;
+        assert!(
+            jump_target > loop_offset,
+            "expected break's jump target ({jump_target}) to land after OP_LOOP ({loop_offset}):\n{listing}"
+        );
+    }
+
+    #[test]
+    fn continue_jumps_back_to_the_loop_test_instead_of_compiling_to_nothing() {
+        let module = parse("function f() { for (let i = 0; i < 10; i++) { if (i) continue; } return 0; }");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let function = unit
+            .constants
+            .iter()
+            .find_map(|c| match c {
+                Constant::Function(f) => Some(f),
+                _ => None,
+            })
+            .expect("expected a Function constant");
+        let listing = Compiler::disassemble(&function.bytecode, &function.constants).expect("disassemble failed");
+        // one unconditional jump from the `if`'s implicit empty-else skip,
+        // plus one from `continue` itself - without a real continue
+        // implementation only the first would be emitted
+        assert_eq!(
+            listing.matches("OP_JUMP ").count(),
+            2,
+            "expected continue to compile to its own unconditional jump:\n{listing}"
+        );
+    }
+
+    #[test]
+    fn break_outside_a_loop_is_a_compile_error_not_a_silent_no_op() {
+        let module = parse("break;");
+        let result = Compiler::new().compile(&module);
+        assert!(matches!(result, Err(CompileError::BreakOutsideLoop)));
+    }
+
+    #[test]
+    fn continue_outside_a_loop_is_a_compile_error_not_a_silent_no_op() {
+        let module = parse("continue;");
+        let result = Compiler::new().compile(&module);
+        assert!(matches!(result, Err(CompileError::ContinueOutsideLoop)));
+    }
+
+    #[test]
+    fn prefix_and_postfix_update_on_a_global_resolve_through_the_constant_pool() {
+        let module = parse("function f() { i++; return ++i; }");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let function = unit
+            .constants
+            .iter()
+            .find_map(|c| match c {
+                Constant::Function(f) => Some(f),
+                _ => None,
+            })
+            .expect("expected a Function constant");
+        let listing = Compiler::disassemble(&function.bytecode, &function.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_STORE_VAR"), "expected the update to store back to the global:\n{listing}");
+    }
+
+    #[test]
+    fn fn_decl_compiles_to_closure_constant_with_implicit_return() {
+        let module = parse("function add(a, b) { return a; } add(1, 2);");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+
+        let function = unit
+            .constants
+            .iter()
+            .find_map(|c| match c {
+                Constant::Function(f) => Some(f),
+                _ => None,
+            })
+            .expect("expected a Function constant");
+        assert_eq!(function.name, "add");
+        assert_eq!(function.arity, 2);
+        // `return a;` is still followed by the implicit fall-off-the-end return
+        assert!(function
+            .bytecode
+            .ends_with(&[Operation::Undefined.get_opcode(), Operation::Return.get_opcode()]));
+
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_CLOSURE"));
+        assert!(listing.contains("OP_CALL"));
+    }
+
+    #[test]
+    fn fn_body_falls_off_end_with_implicit_undefined_return() {
+        let module = parse("function noop() {}");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let function = unit
+            .constants
+            .iter()
+            .find_map(|c| match c {
+                Constant::Function(f) => Some(f),
+                _ => None,
+            })
+            .expect("expected a Function constant");
+        let listing = Compiler::disassemble(&function.bytecode, &function.constants).expect("disassemble failed");
+        assert!(listing.contains("OP_UNDEFINED"));
+        assert!(listing.ends_with(&format!("{:04} OP_RETURN\n", function.bytecode.len() - 1)));
+    }
+
+    // writes `contents` to a fresh file under a per-test scratch directory so
+    // compile_program/compile_file can exercise real filesystem loading
+    fn write_temp_file(dir: &str, name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join("kumojs_test").join(dir);
+        std::fs::create_dir_all(&path).expect("failed to create scratch dir");
+        let path = path.join(name);
+        std::fs::write(&path, contents).expect("failed to write scratch file");
+        path
+    }
+
+    #[test]
+    fn compile_program_compiles_entry_and_its_import() {
+        let entry = write_temp_file(
+            "compile_program_compiles_entry_and_its_import",
+            "entry.js",
+            "import \"dep.js\";",
+        );
+        write_temp_file(
+            "compile_program_compiles_entry_and_its_import",
+            "dep.js",
+            "1;",
+        );
+
+        let mut next_id: FileId = 1;
+        let program = Compiler::compile_program(&entry, |_specifier, _path, _kind| {
+            let id = next_id;
+            next_id += 1;
+            Ok(id)
+        })
+        .expect("compile_program failed");
+
+        assert_eq!(program.units.len(), 2);
+    }
+
+    #[test]
+    fn compile_program_terminates_on_circular_imports() {
+        let entry = write_temp_file(
+            "compile_program_terminates_on_circular_imports",
+            "a.js",
+            "import \"b.js\";",
+        );
+        write_temp_file(
+            "compile_program_terminates_on_circular_imports",
+            "b.js",
+            "import \"a.js\";",
+        );
+
+        // a real loader assigns ids by the resolved, canonical file path (not
+        // the raw specifier text), so re-requesting the same file - however
+        // it was spelled - returns the id it was already assigned
+        let mut assigned: HashMap<PathBuf, FileId> = HashMap::new();
+        assigned.insert(entry.clone(), 0);
+        let mut next_id: FileId = 1;
+        let program = Compiler::compile_program(&entry, |_specifier, path, _kind| {
+            let id = *assigned.entry(path.to_path_buf()).or_insert_with(|| {
+                let id = next_id;
+                next_id += 1;
+                id
+            });
+            Ok(id)
+        })
+        .expect("compile_program should terminate instead of looping forever");
+
+        assert_eq!(program.units.len(), 2);
+    }
+
+    #[test]
+    fn import_without_a_loader_is_a_compile_error_not_a_panic() {
+        let entry = write_temp_file(
+            "import_without_a_loader_is_a_compile_error_not_a_panic",
+            "entry.js",
+            "import \"dep.js\";",
+        );
+
+        let result = Compiler::new().compile_file(&entry);
+        assert!(matches!(result, Err(CompileError::LoaderError(_))));
+    }
+
+    #[test]
+    fn disassemble_reports_every_instruction_with_its_byte_offset() {
+        let module = parse("1 + 2;");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+
+        // two loads, an add, and a pop - one disassembled line each
+        assert_eq!(listing.lines().count(), 4);
+        // the listing's own offsets must match where each opcode actually is
+        let mut offset = 0usize;
+        for line in listing.lines() {
+            let prefix = format!("{offset:04} ");
+            assert!(line.starts_with(&prefix), "line {line:?} missing offset {prefix:?}");
+            let opcode = unit.bytecode[offset];
+            let (_, kind, _) = Operation::decode_opcode(opcode).expect("known opcode");
+            offset += 1 + match kind {
+                OperandKind::None => 0,
+                OperandKind::Bool => 1,
+                OperandKind::U16 => 2,
+                OperandKind::TwoStrings => unreachable!("not exercised by this program"),
+            };
+        }
+        assert_eq!(offset, unit.bytecode.len());
+    }
+
+    #[test]
+    fn disassemble_resolves_load_const_operand_to_its_value() {
+        let module = parse("\"hello\";");
+        let unit = Compiler::new().compile(&module).expect("compile failed");
+        let listing = Compiler::disassemble(&unit.bytecode, &unit.constants).expect("disassemble failed");
+        assert!(
+            listing.contains("OP_LOAD_CONST 0 \"hello\""),
+            "expected the constant pool value inline, got:\n{listing}"
+        );
+    }
+
+    #[test]
+    fn disassemble_errors_on_truncated_operand() {
+        // OP_LOAD_CONST (0x01) demands a 2-byte operand but only gets one
+        let truncated = vec![0x01, 0x00];
+        let err = Compiler::disassemble(&truncated, &[]).expect_err("expected a truncation error");
+        assert!(matches!(err, CompileError::DisassembleError(_)));
+    }
+
+    #[test]
+    fn disassemble_errors_on_unknown_opcode() {
+        let unknown = vec![0xff];
+        let err = Compiler::disassemble(&unknown, &[]).expect_err("expected an unknown-opcode error");
+        assert!(matches!(err, CompileError::DisassembleError(_)));
+    }
+
+    #[test]
+    fn unreachable_code_after_return_is_flagged() {
+        let module = parse("function f() { return 1; 2; }");
+        let diagnostics = Compiler::analyze(&module);
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.message.contains("unreachable code")));
+    }
+
+    #[test]
+    fn use_before_declaration_in_same_block_is_flagged() {
+        let module = parse("{ x; let x = 1; }");
+        let diagnostics = Compiler::analyze(&module);
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error && d.message.contains("used before")));
+    }
+
+    #[test]
+    fn use_before_declaration_from_a_nested_block_is_flagged() {
+        // `x` is read from a nested block before the enclosing block's `let x`
+        // is reached - a real TDZ violation even though it isn't the
+        // innermost open scope that declares `x`
+        let module = parse("{ { x; } let x = 1; }");
+        let diagnostics = Compiler::analyze(&module);
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error && d.message.contains("used before")));
+    }
+
+    #[test]
+    fn unused_bindings_in_non_overlapping_scopes_are_both_flagged() {
+        // same name, two sibling blocks - a flat name-keyed map would let the
+        // second declaration's insert silently clobber the first's entry
+        let module = parse("{ let x = 1; } { let x = 2; }");
+        let diagnostics = Compiler::analyze(&module);
+        let unused_count = diagnostics
+            .iter()
+            .filter(|d| d.message.contains("unused variable `x`"))
+            .count();
+        assert_eq!(unused_count, 2);
+    }
+
+    #[test]
+    fn used_binding_is_not_flagged_as_unused() {
+        let module = parse("{ let x = 1; x; }");
+        let diagnostics = Compiler::analyze(&module);
+        assert!(!diagnostics.iter().any(|d| d.message.contains("unused variable")));
     }
 }
@@ -11,9 +11,9 @@ fn main() {
     let compilation = compiler.compile_file(&Path::new("scripts/example.js"));
 
     match compilation {
-        Ok(bytecode) => {
+        Ok(unit) => {
             let bytecode_json =
-                serde_json::to_string(&bytecode).expect("failed to serialize bytecode");
+                serde_json::to_string(&unit).expect("failed to serialize bytecode");
 
             let mut file = File::create(Path::new("vm/bytecode.json"))
                 .expect("failed to create bytecode file");
@@ -21,7 +21,7 @@ fn main() {
             file.write_all(bytecode_json.as_bytes())
                 .expect("failed to write to bytecode file");
 
-            println!("{:?}", bytecode);
+            println!("{:?}", unit);
         }
         Err(e) => println!("{:?}", e),
     }